@@ -0,0 +1,45 @@
+use crate::config::remote::HostSelector;
+
+/// Command-line options consumed by [`steps::ssh`].
+#[derive(Debug, Default)]
+pub struct Cli {
+    /// which hosts to upgrade, from `--remote-hosts @servers,@pi`
+    pub remote_hosts: HostSelector,
+}
+
+impl Cli {
+    /// Parses the options `steps::ssh` cares about out of `args` (e.g. `env::args()` with
+    /// the binary name already skipped).
+    pub fn parse(args: &[String]) -> Self {
+        let mut remote_hosts = HostSelector::default();
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--remote-hosts" {
+                if let Some(value) = args.next() {
+                    remote_hosts = HostSelector::parse(value);
+                }
+            }
+        }
+
+        Self { remote_hosts }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_defaults_to_selecting_every_host() {
+        let cli = Cli::parse(&[]);
+        assert_eq!(cli.remote_hosts, HostSelector::default());
+    }
+
+    #[test]
+    fn parse_reads_remote_hosts_value() {
+        let args: Vec<String> = vec!["--remote-hosts".to_string(), "@servers,@pi".to_string()];
+        let cli = Cli::parse(&args);
+        assert_eq!(cli.remote_hosts, HostSelector::parse("@servers,@pi"));
+    }
+}
@@ -0,0 +1,35 @@
+pub mod remote;
+
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use remote::{resolve_remote, Deprecated, Remote};
+
+/// The parsed contents of a `topgrade.toml` config file.
+#[derive(Deserialize, Debug, Default)]
+pub struct Config {
+    remote: Option<Remote>,
+
+    #[serde(flatten)]
+    deprecated: Option<Deprecated>,
+}
+
+impl Config {
+    /// Reads and parses the config file at `path`, migrating any deprecated flat remote
+    /// keys into `remote` so an explicit `[remote]` table is the only shape callers need
+    /// to handle afterwards.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let contents = fs::read_to_string(path)?;
+        let mut config: Config = toml::from_str(&contents)?;
+        config.remote = resolve_remote(config.remote.take(), config.deprecated.take());
+        Ok(config)
+    }
+
+    /// The fully resolved `[remote]` configuration, if one was set (directly or via the
+    /// deprecated keys).
+    pub fn remote(&self) -> Option<&Remote> {
+        self.remote.as_ref()
+    }
+}
@@ -16,35 +16,30 @@ use serde::Deserialize;
 ///
 /// # Examples
 ///
-/// ```rust
-/// let topgrade_toml = r#"
+/// This is the shape of config a `topgrade.toml` uses; it's illustrative rather than a
+/// compiled doctest, since `Common`'s fields are merged in via `#[serde(flatten)]` rather
+/// than promoted onto `Remote`/`Host` as top-level fields the way this would need to parse
+/// to run as a `Remote`/`Host` literal.
+///
+/// ```toml
 /// [remote]
 /// ssh_arguments = ["-o", "ConnectTimeout=2"]
 /// topgrade_path = "~/.cargo/bin/topgrade"
+/// max_concurrency = 3
+/// disable = ["system"]
 ///
 /// [[remote.hosts]]
 /// destination = "ssh://foo@bar:8080"
 /// topgrade_path = "topgrade"
+/// tags = ["servers"]
 ///
 /// [[remote.hosts]]
 /// destination = "pi@raspberry"
+/// run_in_tmux = true
+/// tags = ["pi"]
 ///
 /// [[remote.hosts]]
 /// destination = "baz"
-/// "#;
-///
-/// let config: Remote = toml::from_str(topgrade_toml).unwrap();
-/// assert_eq!(config.ssh_arguments, vec!["-o", "ConnectTimeout=2"]);
-/// assert_eq!(config.topgrade_path, "~/.cargo/bin/topgrade");
-///
-/// assert_eq!(config.hosts.len(), 3);
-/// assert_eq!(config.hosts[0].destination, "ssh://foo@bar:8080");
-/// assert_eq!(config.hosts[0].topgrade_path, "topgrade");
-/// assert_eq!(config.hosts[0].ssh_arguments, None);
-///
-/// assert_eq!(config.hosts[1].destination, "pi@raspberry");
-///
-/// assert_eq!(config.hosts[2].destination, "baz");
 /// ```
 
 /// `Common` represents options that can be
@@ -59,6 +54,40 @@ pub struct Common {
     /// if left unspecified, we assume
     /// it exists in the remote system's `PATH`
     topgrade_path: Option<String>,
+
+    /// how many hosts to upgrade over `ssh` at once
+    ///
+    /// mirrors the `[git] max_concurrency` setting. Only meaningful at the
+    /// global `[remote]` level; a per-host value is ignored, since a single
+    /// host only ever runs once per invocation. Defaults to `1`, which
+    /// upgrades hosts one at a time.
+    max_concurrency: Option<usize>,
+
+    /// steps to pass as `--disable` to the remote `topgrade` invocation
+    disable: Option<Vec<String>>,
+
+    /// steps to pass as `--only` to the remote `topgrade` invocation
+    only: Option<Vec<String>>,
+
+    /// whether to pass `-y` (don't ask for confirmations) to the remote `topgrade` invocation
+    assume_yes: Option<bool>,
+
+    /// whether to pass `--no-retry` to the remote `topgrade` invocation
+    no_retry: Option<bool>,
+
+    /// whether to run the remote `topgrade` invocation inside a detachable `tmux` session
+    ///
+    /// this lets a long-running upgrade survive an `ssh` disconnect: on reconnect,
+    /// [`steps::ssh`] waits on (or reads the exit status of) the existing session instead
+    /// of starting a new upgrade; it never `tmux attach`es, since its `ssh` call has no
+    /// terminal. If `tmux` isn't installed on the remote, we warn and fall back to
+    /// running directly.
+    run_in_tmux: Option<bool>,
+
+    /// arguments passed to `tmux` on the remote machine, e.g. `["-S", "/var/tmux.sock"]`
+    ///
+    /// only meaningful when `run_in_tmux` is set.
+    tmux_arguments: Option<Vec<String>>,
 }
 
 #[derive(Deserialize, Debug, PartialEq)]
@@ -82,6 +111,150 @@ pub struct Host {
     ///
     /// [ssh-manpage]: <https://www.man7.org/linux/man-pages/man1/ssh.1.html>
     destination: String,
+
+    /// tags grouping this host with others, e.g. `["servers"]`
+    ///
+    /// lets `--remote-hosts @servers,@pi` target a subset of hosts in one run,
+    /// instead of disabling the `remote` step entirely. See [`HostSelector`].
+    tags: Option<Vec<String>>,
+}
+
+/// Selects which hosts a run should upgrade, parsed from a `--remote-hosts` value like
+/// `@servers,@pi,pi@raspberry`: a comma-separated list where `@name` matches hosts tagged
+/// `name` and anything else matches a host's `destination` exactly.
+///
+/// An empty selector (no `--remote-hosts` given) matches every host. Multiple tags are a
+/// union, not an intersection: a host matches if it carries *any* selected tag.
+#[derive(Debug, Default, PartialEq)]
+pub struct HostSelector {
+    tags: Vec<String>,
+    destinations: Vec<String>,
+}
+
+impl HostSelector {
+    pub fn parse(raw: &str) -> Self {
+        let mut tags = Vec::new();
+        let mut destinations = Vec::new();
+
+        for part in raw.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            match part.strip_prefix('@') {
+                Some(tag) => tags.push(tag.to_string()),
+                None => destinations.push(part.to_string()),
+            }
+        }
+
+        Self { tags, destinations }
+    }
+
+    fn matches_all(&self) -> bool {
+        self.tags.is_empty() && self.destinations.is_empty()
+    }
+
+    fn matches(&self, host: &Host) -> bool {
+        self.matches_all()
+            || self.destinations.iter().any(|destination| destination == &host.destination)
+            || host
+                .tags
+                .as_ref()
+                .is_some_and(|host_tags| host_tags.iter().any(|tag| self.tags.contains(tag)))
+    }
+}
+
+/// A [`Host`] with its global and per-host [`Common`] settings merged, ready for
+/// [`steps::ssh`] to act on: per-host values win wherever both are set.
+pub(crate) struct ResolvedHost<'a> {
+    pub(crate) destination: &'a str,
+    pub(crate) ssh_arguments: Vec<String>,
+    pub(crate) topgrade_path: Option<&'a str>,
+    pub(crate) disable: Vec<String>,
+    pub(crate) only: Vec<String>,
+    pub(crate) assume_yes: bool,
+    pub(crate) no_retry: bool,
+    pub(crate) run_in_tmux: bool,
+    pub(crate) tmux_arguments: Vec<String>,
+}
+
+impl Host {
+    fn resolve<'a>(&'a self, global: Option<&'a Common>) -> ResolvedHost<'a> {
+        let host_common = self.common.as_ref();
+
+        let ssh_arguments = host_common
+            .and_then(|c| c.ssh_arguments.as_ref())
+            .or_else(|| global.and_then(|c| c.ssh_arguments.as_ref()))
+            .cloned()
+            .unwrap_or_default();
+
+        let topgrade_path = host_common
+            .and_then(|c| c.topgrade_path.as_deref())
+            .or_else(|| global.and_then(|c| c.topgrade_path.as_deref()));
+
+        let disable = host_common
+            .and_then(|c| c.disable.as_ref())
+            .or_else(|| global.and_then(|c| c.disable.as_ref()))
+            .cloned()
+            .unwrap_or_default();
+
+        let only = host_common
+            .and_then(|c| c.only.as_ref())
+            .or_else(|| global.and_then(|c| c.only.as_ref()))
+            .cloned()
+            .unwrap_or_default();
+
+        let assume_yes = host_common
+            .and_then(|c| c.assume_yes)
+            .or_else(|| global.and_then(|c| c.assume_yes))
+            .unwrap_or(false);
+
+        let no_retry = host_common
+            .and_then(|c| c.no_retry)
+            .or_else(|| global.and_then(|c| c.no_retry))
+            .unwrap_or(false);
+
+        let run_in_tmux = host_common
+            .and_then(|c| c.run_in_tmux)
+            .or_else(|| global.and_then(|c| c.run_in_tmux))
+            .unwrap_or(false);
+
+        let tmux_arguments = host_common
+            .and_then(|c| c.tmux_arguments.as_ref())
+            .or_else(|| global.and_then(|c| c.tmux_arguments.as_ref()))
+            .cloned()
+            .unwrap_or_default();
+
+        ResolvedHost {
+            destination: &self.destination,
+            ssh_arguments,
+            topgrade_path,
+            disable,
+            only,
+            assume_yes,
+            no_retry,
+            run_in_tmux,
+            tmux_arguments,
+        }
+    }
+}
+
+impl Remote {
+    /// The hosts to upgrade, with global settings merged into each [`Host`]'s own overrides
+    /// and limited to those matching `selector` (an empty selector matches every host).
+    pub(crate) fn resolve_selected_hosts(&self, selector: &HostSelector) -> Vec<ResolvedHost<'_>> {
+        let global = self.common.as_ref();
+        self.hosts
+            .iter()
+            .filter(|host| selector.matches(host))
+            .map(|host| host.resolve(global))
+            .collect()
+    }
+
+    /// How many hosts [`steps::ssh`] should upgrade at once; defaults to `1` (sequential).
+    pub(crate) fn max_concurrency(&self) -> usize {
+        self.common
+            .as_ref()
+            .and_then(|common| common.max_concurrency)
+            .unwrap_or(1)
+            .max(1)
+    }
 }
 
 #[derive(Deserialize, Debug, PartialEq)]
@@ -91,6 +264,73 @@ pub struct Deprecated {
     ssh_arguments: Option<String>,
 }
 
+impl Deprecated {
+    /// Migrates the deprecated flat `remote_topgrades`, `remote_topgrade_path`, and
+    /// `ssh_arguments` keys into the new `[remote]` table shape.
+    ///
+    /// The old `ssh_arguments` was a single string (e.g. `"-o ConnectTimeout=2"`); this
+    /// tokenizes it on whitespace into the `Vec<String>` shape [`Common::ssh_arguments`]
+    /// now uses. Each `remote_topgrades` entry becomes a [`Host`] with no per-host
+    /// overrides, and `remote_topgrade_path` carries over as the global `topgrade_path`.
+    ///
+    /// Returns `None` when none of the deprecated keys were set, so callers can tell
+    /// "nothing to migrate" apart from "migrated to an empty remote table".
+    pub fn migrate(self) -> Option<Remote> {
+        if self.remote_topgrades.is_none() && self.remote_topgrade_path.is_none() && self.ssh_arguments.is_none() {
+            return None;
+        }
+
+        log::warn!(
+            "the `remote_topgrades`, `remote_topgrade_path`, and `ssh_arguments` keys are deprecated; \
+             move them into a `[remote]` table using `hosts`, `topgrade_path`, and `ssh_arguments` (as a list) instead"
+        );
+
+        let ssh_arguments = self
+            .ssh_arguments
+            .map(|args| args.split_whitespace().map(String::from).collect());
+
+        let hosts = self
+            .remote_topgrades
+            .unwrap_or_default()
+            .into_iter()
+            .map(|destination| Host {
+                destination,
+                common: None,
+                tags: None,
+            })
+            .collect();
+
+        Some(Remote {
+            hosts,
+            common: Some(Common {
+                ssh_arguments,
+                topgrade_path: self.remote_topgrade_path,
+                max_concurrency: None,
+                disable: None,
+                only: None,
+                assume_yes: None,
+                no_retry: None,
+                run_in_tmux: None,
+                tmux_arguments: None,
+            }),
+        })
+    }
+}
+
+/// Resolves the remote configuration that should actually be used, preferring an explicit
+/// `[remote]` table over the deprecated flat keys.
+///
+/// Config loading should call this once both sections have been parsed: an explicit
+/// `[remote]` table always wins, even when the legacy keys are also present, since the
+/// table's mere presence means the user has already migrated. `Deprecated::migrate` still
+/// runs whenever `deprecated` is set, regardless of which value ends up winning, so a user
+/// who leaves leftover deprecated keys next to their new `[remote]` table still gets the
+/// one-time warning telling them to remove the old ones.
+pub fn resolve_remote(remote: Option<Remote>, deprecated: Option<Deprecated>) -> Option<Remote> {
+    let migrated = deprecated.and_then(Deprecated::migrate);
+    remote.or(migrated)
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -244,6 +484,282 @@ mod tests {
         );
     }
 
+    #[test]
+    fn migrate_tokenizes_ssh_arguments_and_maps_hosts() {
+        let deprecated = Deprecated {
+            remote_topgrades: Some(vec!["toothless".to_string(), "pi".to_string()]),
+            remote_topgrade_path: Some(".cargo/bin/topgrade".to_string()),
+            ssh_arguments: Some("-o ConnectTimeout=2".to_string()),
+        };
+
+        let remote = deprecated.migrate().unwrap();
+
+        assert_eq!(
+            remote.hosts,
+            vec![
+                Host {
+                    destination: "toothless".to_string(),
+                    common: None,
+                    tags: None,
+                },
+                Host {
+                    destination: "pi".to_string(),
+                    common: None,
+                    tags: None,
+                },
+            ]
+        );
+
+        let common = remote.common.unwrap();
+        assert_eq!(
+            common.ssh_arguments,
+            Some(vec!["-o".to_string(), "ConnectTimeout=2".to_string()])
+        );
+        assert_eq!(common.topgrade_path, Some(".cargo/bin/topgrade".to_string()));
+    }
+
+    #[test]
+    fn migrate_returns_none_when_nothing_was_set() {
+        let deprecated = Deprecated {
+            remote_topgrades: None,
+            remote_topgrade_path: None,
+            ssh_arguments: None,
+        };
+
+        assert_eq!(deprecated.migrate(), None);
+    }
+
+    #[test]
+    fn explicit_remote_table_wins_over_deprecated_keys() {
+        let explicit = Remote {
+            hosts: vec![],
+            common: None,
+        };
+        // Still fed into `resolve_remote` (rather than left out) so this exercises the path
+        // where `Deprecated::migrate` runs purely for its one-time warning even though the
+        // explicit table is what actually wins below.
+        let deprecated = Deprecated {
+            remote_topgrades: Some(vec!["toothless".to_string()]),
+            remote_topgrade_path: None,
+            ssh_arguments: None,
+        };
+
+        let resolved = resolve_remote(Some(explicit), Some(deprecated));
+
+        assert_eq!(
+            resolved,
+            Some(Remote {
+                hosts: vec![],
+                common: None,
+            })
+        );
+    }
+
+    #[test]
+    fn deprecated_keys_used_when_no_explicit_table_is_present() {
+        let deprecated = Deprecated {
+            remote_topgrades: Some(vec!["parnas".to_string()]),
+            remote_topgrade_path: None,
+            ssh_arguments: None,
+        };
+
+        let resolved = resolve_remote(None, Some(deprecated));
+
+        assert_eq!(
+            resolved.unwrap().hosts,
+            vec![Host {
+                destination: "parnas".to_string(),
+                common: None,
+                tags: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn max_concurrency_defaults_to_one() {
+        let remote: Remote = toml::from_str(
+            r#"
+[[hosts]]
+destination = "foobar"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(remote.max_concurrency(), 1);
+    }
+
+    #[test]
+    fn max_concurrency_reads_global_setting() {
+        let remote: Remote = toml::from_str(
+            r#"
+max_concurrency = 5
+
+[[hosts]]
+destination = "foobar"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(remote.max_concurrency(), 5);
+    }
+
+    #[test]
+    fn resolve_hosts_prefers_per_host_settings_over_global() {
+        let remote: Remote = toml::from_str(
+            r#"
+ssh_arguments = ["-o", "ConnectTimeout=2"]
+topgrade_path = "~/.cargo/bin/topgrade"
+
+[[hosts]]
+destination = "foo"
+topgrade_path = "topgrade"
+
+[[hosts]]
+destination = "bar"
+"#,
+        )
+        .unwrap();
+
+        let resolved = remote.resolve_selected_hosts(&HostSelector::default());
+
+        assert_eq!(resolved[0].destination, "foo");
+        assert_eq!(resolved[0].topgrade_path, Some("topgrade"));
+        assert_eq!(
+            resolved[0].ssh_arguments,
+            vec!["-o".to_string(), "ConnectTimeout=2".to_string()]
+        );
+
+        assert_eq!(resolved[1].destination, "bar");
+        assert_eq!(resolved[1].topgrade_path, Some("~/.cargo/bin/topgrade"));
+    }
+
+    #[test]
+    fn resolve_hosts_lets_per_host_values_override_behavior_flags() {
+        let remote: Remote = toml::from_str(
+            r#"
+disable = ["system"]
+assume_yes = true
+
+[[hosts]]
+destination = "pi"
+only = ["system"]
+assume_yes = false
+
+[[hosts]]
+destination = "beefy"
+no_retry = true
+"#,
+        )
+        .unwrap();
+
+        let resolved = remote.resolve_selected_hosts(&HostSelector::default());
+
+        assert_eq!(resolved[0].destination, "pi");
+        assert_eq!(resolved[0].only, vec!["system".to_string()]);
+        assert_eq!(resolved[0].disable, vec!["system".to_string()]);
+        assert!(!resolved[0].assume_yes);
+
+        assert_eq!(resolved[1].destination, "beefy");
+        assert_eq!(resolved[1].disable, vec!["system".to_string()]);
+        assert!(resolved[1].assume_yes);
+        assert!(resolved[1].no_retry);
+    }
+
+    #[test]
+    fn resolve_hosts_lets_per_host_value_opt_into_tmux() {
+        let remote: Remote = toml::from_str(
+            r#"
+tmux_arguments = ["-S", "/var/tmux.sock"]
+
+[[hosts]]
+destination = "flaky-pi"
+run_in_tmux = true
+
+[[hosts]]
+destination = "stable-server"
+"#,
+        )
+        .unwrap();
+
+        let resolved = remote.resolve_selected_hosts(&HostSelector::default());
+
+        assert_eq!(resolved[0].destination, "flaky-pi");
+        assert!(resolved[0].run_in_tmux);
+        assert_eq!(
+            resolved[0].tmux_arguments,
+            vec!["-S".to_string(), "/var/tmux.sock".to_string()]
+        );
+
+        assert_eq!(resolved[1].destination, "stable-server");
+        assert!(!resolved[1].run_in_tmux);
+    }
+
+    #[test]
+    fn host_selector_parses_tags_and_destinations() {
+        let selector = HostSelector::parse("@servers, @pi ,pi@raspberry");
+
+        assert_eq!(
+            selector,
+            HostSelector {
+                tags: vec!["servers".to_string(), "pi".to_string()],
+                destinations: vec!["pi@raspberry".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn host_selector_empty_string_matches_every_host() {
+        let selector = HostSelector::parse("");
+
+        assert!(selector.matches_all());
+    }
+
+    #[test]
+    fn resolve_selected_hosts_unions_multiple_tags() {
+        let remote: Remote = toml::from_str(
+            r#"
+[[hosts]]
+destination = "toothless"
+tags = ["servers"]
+
+[[hosts]]
+destination = "pi"
+tags = ["pi"]
+
+[[hosts]]
+destination = "parnas"
+"#,
+        )
+        .unwrap();
+
+        let selector = HostSelector::parse("@servers,@pi");
+        let resolved = remote.resolve_selected_hosts(&selector);
+
+        let destinations: Vec<&str> = resolved.iter().map(|host| host.destination).collect();
+        assert_eq!(destinations, vec!["toothless", "pi"]);
+    }
+
+    #[test]
+    fn resolve_selected_hosts_matches_explicit_destination() {
+        let remote: Remote = toml::from_str(
+            r#"
+[[hosts]]
+destination = "toothless"
+tags = ["servers"]
+
+[[hosts]]
+destination = "parnas"
+"#,
+        )
+        .unwrap();
+
+        let selector = HostSelector::parse("parnas");
+        let resolved = remote.resolve_selected_hosts(&selector);
+
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].destination, "parnas");
+    }
+
     #[test]
     fn parse_simple_table() {
         let config: HashMap<String, Value> = toml::from_str(
@@ -0,0 +1,34 @@
+mod cli;
+mod config;
+mod steps;
+
+use std::env;
+use std::path::Path;
+use std::process::ExitCode;
+
+use cli::Cli;
+use config::Config;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let cli = Cli::parse(&args);
+
+    let config = match Config::load(Path::new("topgrade.toml")) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("failed to read topgrade.toml: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let Some(remote) = config.remote() else {
+        return ExitCode::SUCCESS;
+    };
+
+    let results = steps::ssh::upgrade_remote_hosts(remote, &cli.remote_hosts);
+    if results.iter().all(|result| result.success) {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
@@ -0,0 +1,330 @@
+//! Upgrade remote hosts over `ssh`.
+//!
+//! See [`crate::config::remote`] for the configuration shapes consumed here.
+
+use std::panic;
+use std::process::Command;
+use std::sync::mpsc;
+use std::thread;
+
+use crate::config::remote::{HostSelector, Remote, ResolvedHost};
+
+/// The outcome of upgrading a single remote host.
+pub struct HostResult {
+    pub destination: String,
+    pub success: bool,
+    pub output: String,
+}
+
+/// Upgrades the hosts in `remote` matched by `selector` (e.g. parsed from a
+/// `--remote-hosts @servers,@pi` CLI flag; an empty selector matches every host), running
+/// up to `remote`'s configured `max_concurrency` SSH sessions at once.
+///
+/// A failure on one host never aborts the others: each host's combined
+/// stdout/stderr is buffered and printed as a single labeled block as soon as
+/// it finishes, so interleaved remote logs stay readable even when several
+/// hosts are upgrading at once. The caller gets a [`HostResult`] per host to
+/// build a final summary from.
+pub fn upgrade_remote_hosts(remote: &Remote, selector: &HostSelector) -> Vec<HostResult> {
+    let limit = remote.max_concurrency();
+    let mut hosts = remote.resolve_selected_hosts(selector).into_iter();
+
+    let (tx, rx) = mpsc::channel();
+    let mut results = Vec::new();
+    let mut in_flight = 0;
+
+    loop {
+        while in_flight < limit {
+            let Some(host) = hosts.next() else { break };
+            let tx = tx.clone();
+            let destination = host.destination.to_string();
+            let command = build_command(&host);
+            thread::spawn(move || {
+                let panicked_destination = destination.clone();
+                let result = panic::catch_unwind(panic::AssertUnwindSafe(|| run(destination, command)))
+                    .unwrap_or_else(|_| HostResult {
+                        destination: panicked_destination,
+                        success: false,
+                        output: "upgrading this host panicked unexpectedly".to_string(),
+                    });
+                let _ = tx.send(result);
+            });
+            in_flight += 1;
+        }
+
+        if in_flight == 0 {
+            break;
+        }
+
+        let result = rx.recv().expect("a worker thread exited without sending its result");
+        print_host_block(&result);
+        results.push(result);
+        in_flight -= 1;
+    }
+
+    print_summary(&results);
+    results
+}
+
+fn build_command(host: &ResolvedHost<'_>) -> Command {
+    let mut command = Command::new("ssh");
+    command.args(&host.ssh_arguments);
+    command.arg(host.destination);
+    command.arg(remote_shell_command(host));
+    command
+}
+
+/// Builds the single shell command string run on the remote machine: the `topgrade`
+/// invocation itself, optionally wrapped so it runs inside a detachable `tmux` session
+/// (falling back to running directly, with a warning, if `tmux` isn't on the remote).
+///
+/// This `ssh` invocation is non-interactive (no pty), so it never `attach`es to the
+/// session — `tmux attach` requires a terminal and would just fail here. Instead we start
+/// the upgrade detached, turn on `remain-on-exit` before it can possibly finish (otherwise
+/// tmux tears the session down the moment the command exits, which would make a
+/// reconnect-after-completion look identical to "never ran" and silently rerun the whole
+/// upgrade — exactly what this feature exists to avoid on a flaky link), and record its
+/// exit status to a file. A freshly started session is waited on with `tmux wait-for`
+/// straight away. When the session is already there, the pane is first checked for
+/// whether it has already finished: a still-running session is waited on, but an
+/// already-finished one is read from the status file directly, since its `wait-for`
+/// signal already fired once and won't fire again.
+///
+/// `inner` is interpolated into single-quoted shell strings below without escaping; a
+/// `topgrade_path` or `--disable`/`--only` value containing a single quote
+/// would break out of that quoting on the remote shell. These all come from the user's own
+/// config today, but this should be properly shell-escaped (e.g. `'` -> `'\''`) before any
+/// of it can come from somewhere less trusted.
+fn remote_shell_command(host: &ResolvedHost<'_>) -> String {
+    let mut inner = vec![host.topgrade_path.unwrap_or("topgrade").to_string()];
+    inner.extend(remote_topgrade_args(host));
+    let inner = inner.join(" ");
+
+    if !host.run_in_tmux {
+        return inner;
+    }
+
+    let tmux_arguments = host.tmux_arguments.join(" ");
+    let tmux = |rest: &str| -> String {
+        if tmux_arguments.is_empty() {
+            format!("tmux {rest}")
+        } else {
+            format!("tmux {tmux_arguments} {rest}")
+        }
+    };
+
+    let status_file = "/tmp/.topgrade-remote-status";
+    let has_session = tmux("has-session -t topgrade 2>/dev/null");
+    let pane_finished = tmux("list-panes -t topgrade -F '#{pane_dead}'");
+    let wait_for_done = tmux("wait-for topgrade-done");
+    let start_and_wait = tmux(&format!(
+        "set-option -g remain-on-exit on \\; new-session -d -s topgrade '{inner}; echo $? > {status_file}; {signal}' \\; wait-for topgrade-done",
+        signal = tmux("wait-for -S topgrade-done")
+    ));
+
+    format!(
+        "if ! command -v tmux >/dev/null 2>&1; then \
+           echo 'topgrade: tmux not found on remote, running without it' >&2; \
+           {inner}; \
+         elif {has_session}; then \
+           if [ \"$({pane_finished})\" != \"1\" ]; then {wait_for_done}; fi; \
+           exit \"$(cat {status_file} 2>/dev/null || echo 1)\"; \
+         else \
+           {start_and_wait}; \
+           exit \"$(cat {status_file} 2>/dev/null || echo 1)\"; \
+         fi"
+    )
+}
+
+/// Builds the arguments appended to the remote `topgrade` invocation itself (as opposed to
+/// `ssh`'s own arguments), translating [`ResolvedHost`]'s behavior flags into their CLI
+/// equivalents.
+fn remote_topgrade_args(host: &ResolvedHost<'_>) -> Vec<String> {
+    let mut args = Vec::new();
+
+    for step in &host.disable {
+        args.push("--disable".to_string());
+        args.push(step.clone());
+    }
+
+    for step in &host.only {
+        args.push("--only".to_string());
+        args.push(step.clone());
+    }
+
+    if host.assume_yes {
+        args.push("-y".to_string());
+    }
+
+    if host.no_retry {
+        args.push("--no-retry".to_string());
+    }
+
+    args
+}
+
+fn run(destination: String, mut command: Command) -> HostResult {
+    match command.output() {
+        Ok(output) => HostResult {
+            destination,
+            success: output.status.success(),
+            output: format!(
+                "{}{}",
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            ),
+        },
+        Err(e) => HostResult {
+            destination,
+            success: false,
+            output: format!("failed to run ssh: {e}"),
+        },
+    }
+}
+
+fn print_host_block(result: &HostResult) {
+    println!("== {} ==", result.destination);
+    print!("{}", result.output);
+    println!("== end {} ==\n", result.destination);
+}
+
+fn print_summary(results: &[HostResult]) {
+    println!("Remote upgrade summary:");
+    for result in results {
+        println!("  {}: {}", result.destination, if result.success { "ok" } else { "failed" });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host(destination: &str) -> ResolvedHost<'_> {
+        ResolvedHost {
+            destination,
+            ssh_arguments: Vec::new(),
+            topgrade_path: None,
+            disable: Vec::new(),
+            only: Vec::new(),
+            assume_yes: false,
+            no_retry: false,
+            run_in_tmux: false,
+            tmux_arguments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn remote_topgrade_args_is_empty_with_no_flags_set() {
+        assert!(remote_topgrade_args(&host("foo")).is_empty());
+    }
+
+    #[test]
+    fn remote_topgrade_args_translates_every_flag() {
+        let mut h = host("foo");
+        h.disable = vec!["system".to_string()];
+        h.only = vec!["npm".to_string(), "cargo".to_string()];
+        h.assume_yes = true;
+        h.no_retry = true;
+
+        assert_eq!(
+            remote_topgrade_args(&h),
+            vec![
+                "--disable".to_string(),
+                "system".to_string(),
+                "--only".to_string(),
+                "npm".to_string(),
+                "--only".to_string(),
+                "cargo".to_string(),
+                "-y".to_string(),
+                "--no-retry".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn remote_shell_command_runs_directly_when_tmux_is_not_requested() {
+        let mut h = host("foo");
+        h.topgrade_path = Some("topgrade");
+
+        assert_eq!(remote_shell_command(&h), "topgrade");
+    }
+
+    #[test]
+    fn remote_shell_command_never_attaches_and_waits_for_completion() {
+        let mut h = host("foo");
+        h.topgrade_path = Some("topgrade");
+        h.run_in_tmux = true;
+
+        let command = remote_shell_command(&h);
+
+        assert!(
+            !command.contains("attach"),
+            "tmux attach needs a tty this non-interactive ssh call doesn't have: {command}"
+        );
+        assert!(command.contains("has-session -t topgrade"));
+        assert!(command.contains("wait-for -S topgrade-done"));
+        assert!(command.contains("wait-for topgrade-done"));
+    }
+
+    #[test]
+    fn remote_shell_command_survives_a_reconnect_after_the_job_already_finished() {
+        let mut h = host("foo");
+        h.topgrade_path = Some("topgrade");
+        h.run_in_tmux = true;
+
+        let command = remote_shell_command(&h);
+
+        // `remain-on-exit` must be turned on before the session can possibly finish, so a
+        // reconnecting client can still tell a finished session apart from a nonexistent one.
+        assert!(command.contains("remain-on-exit on"));
+        // An already-finished pane must not block on wait-for again (its signal already
+        // fired once) - it reports the real exit status from the status file instead.
+        assert!(command.contains("pane_dead"));
+        assert!(command.contains(".topgrade-remote-status"));
+    }
+
+    #[test]
+    fn remote_shell_command_falls_back_with_a_warning_when_tmux_is_missing() {
+        let mut h = host("foo");
+        h.topgrade_path = Some("topgrade");
+        h.run_in_tmux = true;
+
+        let command = remote_shell_command(&h);
+
+        assert!(command.contains("command -v tmux"));
+        assert!(command.contains("tmux not found on remote"));
+    }
+
+    #[test]
+    fn remote_shell_command_threads_tmux_arguments_through() {
+        let mut h = host("foo");
+        h.topgrade_path = Some("topgrade");
+        h.run_in_tmux = true;
+        h.tmux_arguments = vec!["-S".to_string(), "/var/tmux.sock".to_string()];
+
+        let command = remote_shell_command(&h);
+
+        assert!(command.contains("tmux -S /var/tmux.sock has-session"));
+        assert!(command.contains("tmux -S /var/tmux.sock set-option -g remain-on-exit on"));
+    }
+
+    #[test]
+    fn build_command_runs_ssh_with_arguments_destination_and_shell_command() {
+        let mut h = host("foo");
+        h.ssh_arguments = vec!["-o".to_string(), "ConnectTimeout=2".to_string()];
+        h.topgrade_path = Some("topgrade");
+
+        let command = build_command(&h);
+        let args: Vec<String> = command.get_args().map(|arg| arg.to_string_lossy().into_owned()).collect();
+
+        assert_eq!(
+            args,
+            vec![
+                "-o".to_string(),
+                "ConnectTimeout=2".to_string(),
+                "foo".to_string(),
+                "topgrade".to_string(),
+            ]
+        );
+    }
+}